@@ -0,0 +1,99 @@
+use std::net::IpAddr;
+
+use crate::constants::{DIGEST, IP};
+use crate::protocol::req::AuthPacket;
+
+/// 可插拔的鉴权方式，对应 `addAuth`（opcode 100）里的一种 scheme。
+/// object-safe，方便下游接自定义的 scheme（比如 SASL）
+pub trait AuthProvider: std::fmt::Debug + Send + Sync {
+    /// 鉴权模式名字，比如 `"digest"` `"ip"`
+    fn scheme(&self) -> &str;
+    /// 发给服务端的凭证原文，`digest` 模式下是未加密的 `user:password`
+    fn auth_data(&self) -> Vec<u8>;
+}
+
+/// 内置的 digest 鉴权，和 [`crate::protocol::req::Scheme::Digest`] 对应；
+/// 这里发的是未加密的 `user:password`，由服务端自己算 SHA1+base64 去比对
+#[derive(Clone)]
+pub struct DigestAuthProvider {
+    pub user: String,
+    pub password: String,
+}
+
+// 手写 Debug，避免 `password` 明文出现在日志里
+impl std::fmt::Debug for DigestAuthProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DigestAuthProvider")
+            .field("user", &self.user)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+impl DigestAuthProvider {
+    pub fn new(user: impl Into<String>, password: impl Into<String>) -> Self {
+        DigestAuthProvider {
+            user: user.into(),
+            password: password.into(),
+        }
+    }
+}
+
+impl AuthProvider for DigestAuthProvider {
+    fn scheme(&self) -> &str {
+        DIGEST
+    }
+
+    fn auth_data(&self) -> Vec<u8> {
+        format!("{}:{}", self.user, self.password).into_bytes()
+    }
+}
+
+/// 内置的 ip 鉴权，和 [`crate::protocol::req::Scheme::IP`] 对应，
+/// 用 `IpAddr` 而不是裸字符串，跟 `Scheme::IP` 保持一样的类型安全
+#[derive(Debug, Clone)]
+pub struct IpAuthProvider {
+    pub addr: IpAddr,
+}
+
+impl IpAuthProvider {
+    pub fn new(addr: IpAddr) -> Self {
+        IpAuthProvider { addr }
+    }
+}
+
+impl AuthProvider for IpAuthProvider {
+    fn scheme(&self) -> &str {
+        IP
+    }
+
+    fn auth_data(&self) -> Vec<u8> {
+        self.addr.to_string().into_bytes()
+    }
+}
+
+/// 客户端已注册的鉴权信息，按注册顺序保存；会话（重）建立后，
+/// 紧跟在 `ConnectRequest` 握手后面把它们依次重放一遍，让重连后的 ACL 校验继续生效
+#[derive(Debug, Default)]
+pub struct AuthProviderRegistry {
+    providers: Vec<Box<dyn AuthProvider>>,
+}
+
+impl AuthProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 对应 `addAuth`：登记一个凭证，之后每次（重）建立会话时按登记顺序自动重放；
+    /// 这里只负责记录，不往连接上发任何东西
+    pub fn add_auth(&mut self, provider: Box<dyn AuthProvider>) {
+        self.providers.push(provider);
+    }
+
+    pub(crate) fn packets(&self) -> Vec<AuthPacket> {
+        self.providers
+            .iter()
+            .map(|p| AuthPacket::new(p.scheme(), p.auth_data()))
+            .collect()
+    }
+}
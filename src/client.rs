@@ -0,0 +1,228 @@
+use std::net::SocketAddr;
+
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::auth::{AuthProvider, AuthProviderRegistry};
+use crate::protocol::req::{
+    ensure_writable, AuthPacket, ConnectRequest, ConnectResponse, MultiRequest, MultiResponse,
+    ReplyHeader, RequestHeader, Session, OP_AUTH, OP_MULTI,
+};
+use crate::protocol::Serializer;
+use crate::tls::TlsConfig;
+use crate::ZKResult;
+
+/// 每个 auth 包固定用 xid = -4，这是 ZK 协议里的约定，不是每次递增的请求 xid
+const AUTHPACKET_XID: i32 = -4;
+/// watch 事件推送用的 xid，跟请求/响应的配对无关，是 server 主动推过来的
+const WATCH_XID: i32 = -1;
+
+/// 明文或者 TLS 连接，握手和收发逻辑对两者一视同仁
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+}
+
+/// 客户端跟单台 ZK server 之间的一条连接：握手、断线重连、只读降级、鉴权重放都在这里落地
+pub(crate) struct Connection {
+    addr: SocketAddr,
+    /// 连接字符串里的原始 host（域名或 IP），TLS SNI 用这个而不是解析后的 `addr`
+    host: String,
+    session_timeout: u32,
+    read_only_requested: bool,
+    tls: Option<TlsConfig>,
+    auth: AuthProviderRegistry,
+    session: Session,
+    stream: Option<Stream>,
+}
+
+impl Connection {
+    pub(crate) fn new(addr: SocketAddr, host: impl Into<String>, session_timeout: u32) -> Self {
+        Connection {
+            addr,
+            host: host.into(),
+            session_timeout,
+            read_only_requested: false,
+            tls: None,
+            auth: AuthProviderRegistry::new(),
+            session: Session::default(),
+            stream: None,
+        }
+    }
+
+    /// 要求在集群没有 quorum 时也允许降级成只读会话
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only_requested = read_only;
+        self
+    }
+
+    /// 跟 `session_timeout` 一样是构造时的选项：设置了就在 `ConnectRequest`
+    /// 握手之前把 TCP 流升级成 TLS 流，走加密通道对接生产集群
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// 对应 `addAuth`：注册一个凭证，马上生效，并且每次（重）建立会话时都会自动重放一遍
+    pub async fn add_auth(&mut self, provider: Box<dyn AuthProvider>) -> ZKResult<()> {
+        // 已经连上的话，立刻在当前会话上发一次并等它的回执
+        if let Some(stream) = self.stream.as_mut() {
+            let packet = AuthPacket::new(provider.scheme(), provider.auth_data());
+            let mut buf = BytesMut::new();
+            RequestHeader::new(AUTHPACKET_XID, OP_AUTH).write(&mut buf)?;
+            packet.write(&mut buf)?;
+            write_frame(stream, &buf).await?;
+            self.recv_reply(AUTHPACKET_XID).await?;
+        }
+        self.auth.add_auth(provider);
+        Ok(())
+    }
+
+    /// 建立（或者断线后重新建立）会话：连 TCP、发 `ConnectRequest`、
+    /// 解析 `ConnectResponse` 落到 `Session` 里。已有 session_id 的话走 resume，
+    /// 带上之前保存的 session_id/passwd/last_zxid_seen，而不是重新开一个新会话
+    pub(crate) async fn establish(&mut self) -> ZKResult<()> {
+        let tcp = TcpStream::connect(self.addr).await?;
+        let mut stream = match &self.tls {
+            // SNI/证书校验必须用调用方连接时给的 host（域名），不能用解析后的 IP——
+            // 生产环境的证书基本都是签给域名的，用 IP 做 SNI 几乎必然握手失败
+            Some(tls) => Stream::Tls(Box::new(tls.connect(tcp, &self.host).await?)),
+            None => Stream::Plain(tcp),
+        };
+
+        let connect_req = if self.session.session_id != 0 {
+            ConnectRequest::resume(
+                &self.session,
+                self.session_timeout,
+                self.read_only_requested,
+            )
+        } else {
+            ConnectRequest::new(self.session_timeout).with_read_only(self.read_only_requested)
+        };
+
+        let mut payload = BytesMut::new();
+        connect_req.write(&mut payload)?;
+        write_frame(&mut stream, &payload).await?;
+
+        let mut resp = read_frame(&mut stream).await?;
+        let connect_resp = ConnectResponse::parse(&mut resp)?;
+        self.session.update_from(&connect_resp);
+
+        self.stream = Some(stream);
+
+        // 紧跟在握手后面，把注册过的鉴权信息按顺序重放一遍，这样重连后
+        // 依赖 Scheme::Digest/Scheme::IP 的 ACL 校验才能继续通过。
+        // 每发一个都要等对应的回执，而不是发完就不管——否则这条回执会原地留在
+        // 连接里，被后面第一个真正的请求误当成自己的响应收走，后续全部错位
+        for packet in self.auth.packets() {
+            let header = RequestHeader::new(AUTHPACKET_XID, OP_AUTH);
+            let mut buf = BytesMut::new();
+            header.write(&mut buf)?;
+            packet.write(&mut buf)?;
+            let stream = self
+                .stream
+                .as_mut()
+                .ok_or(crate::error::ZKError::NotConnected)?;
+            write_frame(stream, &buf).await?;
+            self.recv_reply(AUTHPACKET_XID).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 发送一个已经装好 header 的请求；只读会话下会先拦下写类型的请求，
+    /// 而不是让它发出去再被 server 拒绝
+    pub(crate) async fn send(&mut self, header: &RequestHeader, body: &BytesMut) -> ZKResult<()> {
+        ensure_writable(header.rtype(), &self.session)?;
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or(crate::error::ZKError::NotConnected)?;
+        let mut buf = BytesMut::new();
+        header.write(&mut buf)?;
+        buf.extend_from_slice(body);
+        write_frame(stream, &buf).await
+    }
+
+    /// 按 `xid` 把属于 `expected_xid` 的那个响应帧收回来。
+    /// `-4`（auth 回执）和 `-1`（watch 事件推送）跟请求/响应的配对无关，是 server
+    /// 主动插进来的带外帧，这里直接跳过（auth 失败则报错），不会被误当成调用方在等的响应；
+    /// 只有调用方等的正是 `-4` 时，才把 auth 回执返回给它
+    pub(crate) async fn recv_reply(
+        &mut self,
+        expected_xid: i32,
+    ) -> ZKResult<(ReplyHeader, BytesMut)> {
+        loop {
+            let stream = self
+                .stream
+                .as_mut()
+                .ok_or(crate::error::ZKError::NotConnected)?;
+            let mut buf = read_frame(stream).await?;
+            let rh = ReplyHeader::parse(&mut buf)?;
+            self.session.note_zxid_seen(rh.zxid);
+
+            if rh.xid == AUTHPACKET_XID {
+                if rh.err != 0 {
+                    return Err(crate::error::ZKError::AuthFailed(rh.err));
+                }
+                if expected_xid == AUTHPACKET_XID {
+                    return Ok((rh, buf));
+                }
+                continue;
+            }
+            if rh.xid == WATCH_XID {
+                continue;
+            }
+            if rh.xid != expected_xid {
+                return Err(crate::error::ZKError::UnexpectedReply {
+                    expected: expected_xid,
+                    got: rh.xid,
+                });
+            }
+            return Ok((rh, buf));
+        }
+    }
+
+    /// 提交一整个 `MultiRequest` 事务，等 server 应答后把每个子操作各自的
+    /// 成功/失败结果还给调用方，而不是只管发出去
+    pub(crate) async fn submit_multi(
+        &mut self,
+        xid: i32,
+        multi: &MultiRequest,
+    ) -> ZKResult<MultiResponse> {
+        let mut body = BytesMut::new();
+        multi.write(&mut body)?;
+        let header = RequestHeader::new(xid, OP_MULTI);
+        self.send(&header, &body).await?;
+        let (_, mut reply_body) = self.recv_reply(xid).await?;
+        MultiResponse::parse(&mut reply_body)
+    }
+}
+
+/// ZK 的帧格式：4 字节大端长度前缀 + payload
+async fn write_frame(stream: &mut Stream, payload: &BytesMut) -> ZKResult<()> {
+    let mut framed = BytesMut::with_capacity(4 + payload.len());
+    framed.put_i32(payload.len() as i32);
+    framed.extend_from_slice(payload);
+    match stream {
+        Stream::Plain(s) => s.write_all(&framed).await?,
+        Stream::Tls(s) => s.write_all(&framed).await?,
+    }
+    Ok(())
+}
+
+async fn read_frame(stream: &mut Stream) -> ZKResult<BytesMut> {
+    let mut len_buf = [0u8; 4];
+    match stream {
+        Stream::Plain(s) => s.read_exact(&mut len_buf).await?,
+        Stream::Tls(s) => s.read_exact(&mut len_buf).await?,
+    };
+    let len = i32::from_be_bytes(len_buf).max(0) as usize;
+    let mut buf = vec![0u8; len];
+    match stream {
+        Stream::Plain(s) => s.read_exact(&mut buf).await?,
+        Stream::Tls(s) => s.read_exact(&mut buf).await?,
+    };
+    Ok(BytesMut::from(&buf[..]))
+}
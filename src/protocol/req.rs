@@ -1,13 +1,24 @@
 use std::hash::Hasher;
 
-use bytes::BytesMut;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use bytes::{Buf, BytesMut};
+use sha1::{Digest as _, Sha1};
 
-use crate::constants::{CreateMode, Perms, ANYONE, DIGEST, IP, SUPER, WORLD};
+use crate::constants::{CreateMode, ANYONE, DIGEST, IP, SUPER, WORLD};
 use crate::protocol::Serializer;
 use crate::ZKResult;
 use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
 
+/// 按照 ZooKeeper digest scheme 的约定，把 `user:password` 做 SHA1 后 base64，
+/// 拼成服务端认识的 `"<user>:<base64(sha1)>"` 形式
+fn digest_id(user: &str, password: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}:{}", user, password).as_bytes());
+    format!("{}:{}", user, BASE64.encode(hasher.finalize()))
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct RequestHeader {
     xid: i32,
@@ -18,6 +29,10 @@ impl RequestHeader {
     pub(crate) fn new(xid: i32, rtype: i32) -> RequestHeader {
         RequestHeader { xid, rtype }
     }
+
+    pub(crate) fn rtype(&self) -> i32 {
+        self.rtype
+    }
 }
 
 impl Serializer for RequestHeader {
@@ -28,6 +43,29 @@ impl Serializer for RequestHeader {
     }
 }
 
+/// 每个响应都带的头部：对应请求的 xid、server 这次操作后的 zxid，以及错误码
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ReplyHeader {
+    pub(crate) xid: i32,
+    pub(crate) zxid: i64,
+    pub(crate) err: i32,
+}
+
+impl ReplyHeader {
+    pub(crate) fn parse(b: &mut BytesMut) -> ZKResult<Self> {
+        if b.remaining() < 16 {
+            return Err(crate::error::ZKError::MalformedResponse(
+                "reply header truncated".to_string(),
+            ));
+        }
+        Ok(ReplyHeader {
+            xid: b.get_i32(),
+            zxid: b.get_i64(),
+            err: b.get_i32(),
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct ConnectRequest {
     protocol_version: i32,
@@ -49,6 +87,25 @@ impl ConnectRequest {
             read_only: false,
         }
     }
+
+    /// 要求服务端在集群没有 quorum 时，也允许以只读模式建立会话
+    pub(crate) fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// 断线重连时带上之前保存的 session_id/passwd/last_zxid_seen，
+    /// 而不是重新开一个新会话，这样 watch 和临时节点才能在重连后继续存活
+    pub(crate) fn resume(session: &Session, session_timeout: u32, read_only: bool) -> Self {
+        ConnectRequest {
+            protocol_version: session.protocol_version,
+            last_zxid_seen: session.last_zxid_seen,
+            time_out: session_timeout,
+            session_id: session.session_id,
+            passwd: Some(session.passwd.clone()),
+            read_only,
+        }
+    }
 }
 
 impl Serializer for ConnectRequest {
@@ -62,30 +119,149 @@ impl Serializer for ConnectRequest {
         Ok(())
     }
 }
-/// ZK 内置的 3 种 scheme
-/// 第 4 种 Super 其实就是特殊的 Digest
-#[derive(Debug)]
+
+/// 服务端对 `ConnectRequest` 的应答：协商后的协议版本、分配的 session_id/passwd、
+/// 实际生效的超时时间，以及这次会话是不是降级成了只读
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ConnectResponse {
+    pub(crate) protocol_version: i32,
+    pub(crate) time_out: u32,
+    pub(crate) session_id: i64,
+    pub(crate) passwd: Vec<u8>,
+    pub(crate) read_only: bool,
+}
+
+impl ConnectResponse {
+    pub(crate) fn parse(b: &mut BytesMut) -> ZKResult<Self> {
+        // protocol_version(4) + time_out(4) + session_id(8) + passwd 长度前缀(4)
+        if b.remaining() < 20 {
+            return Err(crate::error::ZKError::MalformedResponse(
+                "connect response truncated before passwd".to_string(),
+            ));
+        }
+        let protocol_version = b.get_i32();
+        let time_out = b.get_u32();
+        let session_id = b.get_i64();
+        let passwd_len = b.get_i32();
+        if passwd_len < 0 || b.remaining() < passwd_len as usize {
+            return Err(crate::error::ZKError::MalformedResponse(
+                "connect response truncated passwd".to_string(),
+            ));
+        }
+        let passwd = b.split_to(passwd_len as usize).to_vec();
+        // 老版本的 server 不会带这个字段，没有就当作不是只读会话
+        let read_only = b.has_remaining() && b.get_u8() != 0;
+        Ok(ConnectResponse {
+            protocol_version,
+            time_out,
+            session_id,
+            passwd,
+            read_only,
+        })
+    }
+}
+
+/// 客户端持有的会话状态：协商结果 + 重连所需的一切，每次握手成功后更新
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Session {
+    pub(crate) protocol_version: i32,
+    pub(crate) session_id: i64,
+    pub(crate) passwd: Vec<u8>,
+    pub(crate) time_out: u32,
+    pub(crate) last_zxid_seen: i64,
+    pub(crate) read_only: bool,
+}
+
+impl Session {
+    pub(crate) fn update_from(&mut self, resp: &ConnectResponse) {
+        self.protocol_version = resp.protocol_version;
+        self.session_id = resp.session_id;
+        self.passwd = resp.passwd.clone();
+        self.time_out = resp.time_out;
+        self.read_only = resp.read_only;
+    }
+
+    pub(crate) fn note_zxid_seen(&mut self, zxid: i64) {
+        if zxid > self.last_zxid_seen {
+            self.last_zxid_seen = zxid;
+        }
+    }
+}
+
+/// 只读会话下不允许发起写请求，发送前先用这个拦一道，而不是让请求发出去再被服务端拒绝
+pub(crate) fn ensure_writable(rtype: i32, session: &Session) -> ZKResult<()> {
+    let is_write = matches!(rtype, OP_CREATE | OP_DELETE | OP_SET_DATA | OP_MULTI);
+    if session.read_only && is_write {
+        return Err(crate::error::ZKError::ReadOnlySession);
+    }
+    Ok(())
+}
+/// ZK 内置的几种 scheme
 pub enum Scheme {
     World,
     IP(IpAddr),
-    // TODO 拆分成加密前的用户名密码，两个字段
-    Digest(String),
+    Digest { user: String, password: String },
+    /// 特殊的 super 用户，写法和 `Digest` 一致，只是固定用 `super` 身份
+    Super { password: String },
+}
+
+// 手写 Debug，避免 `password` 明文出现在日志里
+impl std::fmt::Debug for Scheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scheme::World => f.debug_struct("World").finish(),
+            Scheme::IP(addr) => f.debug_tuple("IP").field(addr).finish(),
+            Scheme::Digest { user, .. } => f
+                .debug_struct("Digest")
+                .field("user", user)
+                .field("password", &"***")
+                .finish(),
+            Scheme::Super { .. } => f
+                .debug_struct("Super")
+                .field("password", &"***")
+                .finish(),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// ZooKeeper 的权限位，对应 `ZooDefs.Perms`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Permission: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const CREATE = 1 << 2;
+        const DELETE = 1 << 3;
+        const ADMIN = 1 << 4;
+        const ALL = Self::READ.bits() | Self::WRITE.bits() | Self::CREATE.bits() | Self::DELETE.bits() | Self::ADMIN.bits();
+    }
+}
+
+impl From<Permission> for u32 {
+    fn from(perms: Permission) -> Self {
+        perms.bits()
+    }
+}
+
+impl From<u32> for Permission {
+    fn from(bits: u32) -> Self {
+        Permission::from_bits_truncate(bits)
+    }
 }
 
 /// ZooKeeper 权限对象
-/// - `perms`：权限
+/// - `perms`：权限，见 [`Permission`]
 /// - `scheme`：鉴权模式，详情可见 [`Scheme`]
 #[derive(Debug)]
 pub struct ACL {
-    // TODO 该字段应该也是枚举对象或者其他有意义的类型，而不是 u32
-    pub perms: u32,
+    pub perms: Permission,
     pub scheme: Scheme,
     pub id: String,
 }
 
 impl Serializer for ACL {
     fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
-        self.write_u32(self.perms, b);
+        self.write_u32(self.perms.into(), b);
         match &self.scheme {
             Scheme::World => {
                 self.write_string(WORLD, b);
@@ -95,9 +271,13 @@ impl Serializer for ACL {
                 self.write_string(IP, b);
                 self.write_string(addr.to_string().as_str(), b);
             }
-            Scheme::Digest(digest_info) => {
+            Scheme::Digest { user, password } => {
+                self.write_string(DIGEST, b);
+                self.write_string(digest_id(user, password).as_str(), b);
+            }
+            Scheme::Super { password } => {
                 self.write_string(DIGEST, b);
-                self.write_string(digest_info, b);
+                self.write_string(digest_id(SUPER, password).as_str(), b);
             }
         };
         Ok(())
@@ -107,7 +287,7 @@ impl Serializer for ACL {
 impl Default for ACL {
     fn default() -> Self {
         ACL {
-            perms: Perms::All as u32,
+            perms: Permission::ALL,
             scheme: Scheme::World,
             id: ANYONE.to_string(),
         }
@@ -120,6 +300,27 @@ impl ACL {
         // TODO 缓存
         vec![ACL::default()]
     }
+
+    /// world scheme 下只给读写权限
+    pub fn read_write_world() -> ACL {
+        ACL {
+            perms: Permission::READ | Permission::WRITE,
+            scheme: Scheme::World,
+            id: ANYONE.to_string(),
+        }
+    }
+
+    /// digest scheme 的 ACL，声明式地指定权限，而不是手拼权限掩码
+    pub fn digest(user: impl Into<String>, password: impl Into<String>, perms: Permission) -> ACL {
+        let user = user.into();
+        let password = password.into();
+        let id = digest_id(&user, &password);
+        ACL {
+            perms,
+            scheme: Scheme::Digest { user, password },
+            id,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -169,6 +370,273 @@ impl CreateRequest {
     }
 }
 
+// ZK 协议里几个跟事务相关的 opcode，取值见 ZooDefs.OpCode
+const OP_CREATE: i32 = 1;
+const OP_DELETE: i32 = 2;
+const OP_SET_DATA: i32 = 5;
+const OP_CHECK: i32 = 13;
+pub(crate) const OP_MULTI: i32 = 14;
+pub(crate) const OP_AUTH: i32 = 100;
+
+/// `addAuth` 发的鉴权包：`{ type: i32=0, scheme: String, auth: Vec<u8> }`
+#[derive(Debug, Default)]
+pub(crate) struct AuthPacket {
+    scheme: String,
+    auth: Vec<u8>,
+}
+
+impl Serializer for AuthPacket {
+    fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
+        self.write_i32(0, b);
+        self.write_string(self.scheme.as_str(), b);
+        self.write_slice(self.auth.clone(), b);
+        Ok(())
+    }
+}
+
+impl AuthPacket {
+    pub(crate) fn new(scheme: impl Into<String>, auth: Vec<u8>) -> Self {
+        AuthPacket {
+            scheme: scheme.into(),
+            auth,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CheckVersionRequest {
+    path: String,
+    version: i32,
+}
+
+impl Serializer for CheckVersionRequest {
+    fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
+        self.write_string(self.path.as_str(), b);
+        self.write_i32(self.version, b);
+        Ok(())
+    }
+}
+
+impl CheckVersionRequest {
+    pub(crate) fn new(path: String, version: i32) -> Self {
+        CheckVersionRequest { path, version }
+    }
+}
+
+/// `multi` 事务里每个子操作前面的头部，标记这一条是什么类型、是不是最后一条
+#[derive(Debug)]
+struct MultiHeader {
+    op_type: i32,
+    done: bool,
+    err: i32,
+}
+
+impl Serializer for MultiHeader {
+    fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
+        self.write_i32(self.op_type, b);
+        self.write_bool(self.done, b);
+        self.write_i32(self.err, b);
+        Ok(())
+    }
+}
+
+impl MultiHeader {
+    fn op(op_type: i32) -> Self {
+        MultiHeader {
+            op_type,
+            done: false,
+            err: -1,
+        }
+    }
+
+    fn done() -> Self {
+        MultiHeader {
+            op_type: -1,
+            done: true,
+            err: -1,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum MultiOp {
+    Create(CreateRequest),
+    Delete(DeleteRequest),
+    SetData(SetDataRequest),
+    Check(CheckVersionRequest),
+}
+
+impl MultiOp {
+    fn op_type(&self) -> i32 {
+        match self {
+            MultiOp::Create(_) => OP_CREATE,
+            MultiOp::Delete(_) => OP_DELETE,
+            MultiOp::SetData(_) => OP_SET_DATA,
+            MultiOp::Check(_) => OP_CHECK,
+        }
+    }
+}
+
+impl Serializer for MultiOp {
+    fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
+        match self {
+            MultiOp::Create(r) => r.write(b),
+            MultiOp::Delete(r) => r.write(b),
+            MultiOp::SetData(r) => r.write(b),
+            MultiOp::Check(r) => r.write(b),
+        }
+    }
+}
+
+/// 把多个子操作打包成一个原子事务（ZK opcode `multi` = 14），
+/// 要么全部生效，要么全部回滚。
+#[derive(Debug, Default)]
+pub(crate) struct MultiRequest {
+    ops: Vec<MultiOp>,
+}
+
+impl MultiRequest {
+    pub(crate) fn new() -> Self {
+        MultiRequest::default()
+    }
+
+    pub(crate) fn create(
+        mut self,
+        path: String,
+        data: Option<&[u8]>,
+        acl: Vec<ACL>,
+        create_mode: CreateMode,
+    ) -> Self {
+        self.ops.push(MultiOp::Create(CreateRequest::new_full(
+            path,
+            data,
+            acl,
+            create_mode,
+        )));
+        self
+    }
+
+    pub(crate) fn delete(mut self, path: String, version: i32) -> Self {
+        self.ops.push(MultiOp::Delete(DeleteRequest::new(path, version)));
+        self
+    }
+
+    pub(crate) fn set_data(mut self, path: String, data: &[u8], version: i32) -> Self {
+        self.ops
+            .push(MultiOp::SetData(SetDataRequest::new(path, data, version)));
+        self
+    }
+
+    pub(crate) fn check(mut self, path: String, version: i32) -> Self {
+        self.ops
+            .push(MultiOp::Check(CheckVersionRequest::new(path, version)));
+        self
+    }
+}
+
+impl Serializer for MultiRequest {
+    fn write(&self, b: &mut BytesMut) -> ZKResult<()> {
+        for op in &self.ops {
+            MultiHeader::op(op.op_type()).write(b)?;
+            op.write(b)?;
+        }
+        MultiHeader::done().write(b)?;
+        Ok(())
+    }
+}
+
+fn read_string(b: &mut BytesMut) -> ZKResult<String> {
+    if b.remaining() < 4 {
+        return Err(crate::error::ZKError::MalformedResponse(
+            "truncated string length".to_string(),
+        ));
+    }
+    let len = b.get_i32();
+    if len < 0 || b.remaining() < len as usize {
+        return Err(crate::error::ZKError::MalformedResponse(
+            "truncated string body".to_string(),
+        ));
+    }
+    let bytes = b.split_to(len as usize);
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| crate::error::ZKError::MalformedResponse("invalid utf8 in string".to_string()))
+}
+
+// `SetDataResponse` 里 `Stat` 结构体的固定长度（czxid/mzxid/ctime/mtime/ephemeralOwner/pzxid
+// 各 8 字节 + version/cversion/aversion/dataLength/numChildren 各 4 字节）
+const STAT_LEN: usize = 68;
+
+/// `multi` 事务里每个子操作各自的执行结果
+#[derive(Debug)]
+pub(crate) enum MultiOpResult {
+    Created { path: String },
+    Deleted,
+    DataSet,
+    Checked,
+    Failed { err: i32 },
+}
+
+/// 对 `MultiRequest` 的应答：按提交顺序给出每个子操作的结果，
+/// 只要有一个失败，真正的数据变更在 server 端已经被整体回滚
+#[derive(Debug, Default)]
+pub(crate) struct MultiResponse {
+    pub(crate) results: Vec<MultiOpResult>,
+}
+
+impl MultiResponse {
+    pub(crate) fn parse(b: &mut BytesMut) -> ZKResult<Self> {
+        let mut results = Vec::new();
+        loop {
+            if b.remaining() < 9 {
+                return Err(crate::error::ZKError::MalformedResponse(
+                    "multi response truncated before header".to_string(),
+                ));
+            }
+            let op_type = b.get_i32();
+            let done = b.get_u8() != 0;
+            let err = b.get_i32();
+            if done {
+                break;
+            }
+            if err != 0 {
+                // 失败的子操作后面跟着一个 ErrorResponse，就是同一个错误码再写一遍
+                if b.remaining() < 4 {
+                    return Err(crate::error::ZKError::MalformedResponse(
+                        "multi response truncated error body".to_string(),
+                    ));
+                }
+                b.get_i32();
+                results.push(MultiOpResult::Failed { err });
+                continue;
+            }
+            let result = match op_type {
+                OP_CREATE => MultiOpResult::Created {
+                    path: read_string(b)?,
+                },
+                OP_DELETE => MultiOpResult::Deleted,
+                OP_SET_DATA => {
+                    if b.remaining() < STAT_LEN {
+                        return Err(crate::error::ZKError::MalformedResponse(
+                            "multi response truncated stat".to_string(),
+                        ));
+                    }
+                    b.advance(STAT_LEN);
+                    MultiOpResult::DataSet
+                }
+                OP_CHECK => MultiOpResult::Checked,
+                _ => {
+                    return Err(crate::error::ZKError::MalformedResponse(format!(
+                        "unexpected op_type {} in multi response",
+                        op_type
+                    )))
+                }
+            };
+            results.push(result);
+        }
+        Ok(MultiResponse { results })
+    }
+}
+
 pub(crate) const DEATH_PTYPE: i8 = -1;
 
 #[derive(Debug)]
@@ -274,3 +742,65 @@ impl PathRequest {
         PathRequest { path }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    #[test]
+    fn digest_id_matches_zk_test_vector() {
+        // ZooKeeper 官方文档/测试里常用的 super:test 凭证
+        assert_eq!(
+            digest_id("super", "test"),
+            "super:D/InIHSb7yEEbrWz8b9l71RjZJU="
+        );
+    }
+
+    fn write_multi_header(b: &mut BytesMut, op_type: i32, done: bool, err: i32) {
+        b.put_i32(op_type);
+        b.put_u8(done as u8);
+        b.put_i32(err);
+    }
+
+    #[test]
+    fn multi_response_parses_success_results_in_order() {
+        let mut b = BytesMut::new();
+        write_multi_header(&mut b, OP_CREATE, false, 0);
+        b.put_i32(2);
+        b.extend_from_slice(b"/a");
+        write_multi_header(&mut b, OP_CHECK, false, 0);
+        write_multi_header(&mut b, -1, true, -1);
+
+        let resp = MultiResponse::parse(&mut b).unwrap();
+        assert_eq!(resp.results.len(), 2);
+        assert!(matches!(
+            &resp.results[0],
+            MultiOpResult::Created { path } if path == "/a"
+        ));
+        assert!(matches!(resp.results[1], MultiOpResult::Checked));
+    }
+
+    #[test]
+    fn multi_response_reports_per_op_failure() {
+        let mut b = BytesMut::new();
+        write_multi_header(&mut b, OP_CREATE, false, -110);
+        b.put_i32(-110);
+        write_multi_header(&mut b, -1, true, -1);
+
+        let resp = MultiResponse::parse(&mut b).unwrap();
+        assert_eq!(resp.results.len(), 1);
+        assert!(matches!(
+            resp.results[0],
+            MultiOpResult::Failed { err: -110 }
+        ));
+    }
+
+    #[test]
+    fn multi_response_rejects_truncated_buffer() {
+        let mut b = BytesMut::new();
+        b.put_i32(OP_CREATE);
+        // 缺 done/err，以及剩下的 body
+        assert!(MultiResponse::parse(&mut b).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use std::io;
+use std::sync::Arc;
+
+use rustls::pki_types::ServerName;
+use rustls::ClientConfig;
+use tokio::net::TcpStream;
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+use crate::ZKResult;
+
+/// 连接 ZK 集群时可选的 TLS 配置，和 `session_timeout` 一样是客户端构造时的选项。
+/// 不设置就走明文 TCP；设置了的话，`ConnectRequest` 握手之前先把 TCP 流包成 TLS 流，
+/// 序列化的内容完全不变，只是换了个字节的出入口。
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// CA 根证书、可选的双向认证客户端证书，由调用方按需构造
+    client_config: Arc<ClientConfig>,
+    /// SNI / 证书校验用的 server name，不设置就用连接地址的 host
+    server_name: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new(client_config: ClientConfig) -> Self {
+        TlsConfig {
+            client_config: Arc::new(client_config),
+            server_name: None,
+        }
+    }
+
+    /// 覆盖默认的 SNI / 证书校验用的 server name
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    /// 在 `ConnectRequest` 握手之前把普通 TCP 流升级成 TLS 流
+    pub(crate) async fn connect(
+        &self,
+        stream: TcpStream,
+        default_server_name: &str,
+    ) -> ZKResult<TlsStream<TcpStream>> {
+        let name = self.server_name.as_deref().unwrap_or(default_server_name);
+        let server_name = ServerName::try_from(name.to_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let connector = TlsConnector::from(self.client_config.clone());
+        let tls_stream = connector.connect(server_name, stream).await?;
+        Ok(tls_stream)
+    }
+}